@@ -0,0 +1,182 @@
+//! A `serde::Deserializer` over the flat `HashMap<String, String>` backing
+//! an `Environment`, modeled on the approach cargo's `Config::get::<T>`
+//! uses to turn string-valued config into typed structs.
+
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{self, IntoDeserializer};
+use serde::forward_to_deserialize_any;
+
+use error::{Error, ErrorKind, Result};
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ErrorKind::InvalidValue(String::new(), msg.to_string()).into()
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ErrorKind::InvalidValue(field.to_string(), "a value".to_string()).into()
+    }
+}
+
+/// Deserializes a target struct directly from the property map.
+pub(crate) struct PropsDeserializer<'a> {
+    props: &'a HashMap<String, String>,
+}
+
+impl<'a> PropsDeserializer<'a> {
+    pub(crate) fn new(props: &'a HashMap<String, String>) -> Self {
+        PropsDeserializer { props }
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for PropsDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_map(PropsMapAccess {
+            iter: self.props.iter(),
+            key: None,
+            value: None,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct enum identifier ignored_any
+    }
+}
+
+/// Walks the property map, handing each key/value pair to serde one at a
+/// time.
+struct PropsMapAccess<'a> {
+    iter: hash_map::Iter<'a, String, String>,
+    key: Option<&'a str>,
+    value: Option<&'a str>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for PropsMapAccess<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.key = Some(key.as_str());
+                self.value = Some(value.as_str());
+                seed.deserialize(key.clone().into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let key = self.key.take().expect("next_value_seed called before next_key_seed");
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(ValueDeserializer { key, value })
+    }
+}
+
+/// Coerces a single string property value into whatever scalar type the
+/// target field asks for.
+struct ValueDeserializer<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+impl<'a> ValueDeserializer<'a> {
+    fn invalid(&self, expected: &str) -> Error {
+        ErrorKind::InvalidValue(self.key.to_string(), expected.to_string()).into()
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($method:ident, $visit:ident, $ty:ty, $expected:expr) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: de::Visitor<'de>,
+        {
+            let parsed: $ty = self.value.parse().map_err(|_e| self.invalid($expected))?;
+            visitor.$visit(parsed)
+        }
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_str(self.value)
+    }
+
+    deserialize_parsed!(deserialize_bool, visit_bool, bool, "bool");
+    deserialize_parsed!(deserialize_i8, visit_i8, i8, "i8");
+    deserialize_parsed!(deserialize_i16, visit_i16, i16, "i16");
+    deserialize_parsed!(deserialize_i32, visit_i32, i32, "i32");
+    deserialize_parsed!(deserialize_i64, visit_i64, i64, "i64");
+    deserialize_parsed!(deserialize_u8, visit_u8, u8, "u8");
+    deserialize_parsed!(deserialize_u16, visit_u16, u16, "u16");
+    deserialize_parsed!(deserialize_u32, visit_u32, u32, "u32");
+    deserialize_parsed!(deserialize_u64, visit_u64, u64, "u64");
+    deserialize_parsed!(deserialize_f32, visit_f32, f32, "f32");
+    deserialize_parsed!(deserialize_f64, visit_f64, f64, "f64");
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_str(self.value)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_string(self.value.to_string())
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        char bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
@@ -3,6 +3,9 @@
 error_chain!{
     foreign_links {
         Io(::std::io::Error);
+        Yaml(::serde_yaml::Error);
+        Toml(::toml::de::Error);
+        Json(::serde_json::Error);
     }
 
     errors {
@@ -18,5 +21,29 @@ error_chain!{
             description("Invalid property found!")
             display("Invalid property found!")
         }
+        InterpolationCycle(key: String) {
+            description("A property interpolates itself, directly or transitively!")
+            display("Property '{}' interpolates itself, directly or transitively!", key)
+        }
+        UndefinedReference(name: String) {
+            description("A property references an undefined key!")
+            display("Property references undefined key '{}'!", name)
+        }
+        InvalidValue(key: String, expected: String) {
+            description("Unable to parse a property value into the requested type!")
+            display("Unable to parse value for '{}' into a '{}'!", key, expected)
+        }
+        AmbiguousEnvOverride(env_name: String, keys: Vec<String>) {
+            description("Multiple properties normalize to the same override environment variable!")
+            display("Environment variable '{}' could override any of {:?}; rename one of the properties to disambiguate!", env_name, keys)
+        }
+        NonMapDocument {
+            description("A structured property file did not have a map at its root!")
+            display("A structured property file must have a map (object) at its root!")
+        }
+        UnflattenableValue(key: String) {
+            description("A structured property file contained an array, which cannot be flattened into a scalar property!")
+            display("Unable to flatten '{}' into a scalar property: arrays are not supported!", key)
+        }
     }
 }
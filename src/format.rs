@@ -0,0 +1,83 @@
+//! Parsing of structured (YAML/TOML/JSON) property files into the flat
+//! `HashMap<String, String>` that backs an `Environment`, so that the
+//! common+kind merge and `Kind` selection logic stays format-agnostic.
+
+use std::collections::HashMap;
+
+use error::{ErrorKind, Result};
+
+/// The file format a property file is written in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Format {
+    /// `key=value` lines (the default).
+    Env,
+    /// A YAML document, flattened into dotted keys.
+    Yaml,
+    /// A TOML document, flattened into dotted keys.
+    Toml,
+    /// A JSON document, flattened into dotted keys.
+    Json,
+}
+
+impl Format {
+    /// The file suffix used for property files of this `Format`.
+    pub(crate) fn suffix(&self) -> &'static str {
+        match *self {
+            Format::Env => ".env",
+            Format::Yaml => ".yaml",
+            Format::Toml => ".toml",
+            Format::Json => ".json",
+        }
+    }
+}
+
+/// Parse a structured (non-`Env`) property file, flattening nested maps
+/// into dotted keys (`db.host`) and stringifying scalar leaves.
+///
+/// The document must have a map at its root, since a flat
+/// `HashMap<String, String>` has nowhere to put a bare top-level scalar or
+/// array.
+pub(crate) fn parse_structured(format: Format, contents: &str) -> Result<HashMap<String, String>> {
+    let value: serde_json::Value = match format {
+        Format::Yaml => serde_json::to_value(serde_yaml::from_str::<serde_yaml::Value>(contents)?)?,
+        Format::Toml => serde_json::to_value(toml::from_str::<toml::Value>(contents)?)?,
+        Format::Json => serde_json::from_str(contents)?,
+        Format::Env => unreachable!("parse_structured is never called for Format::Env"),
+    };
+
+    if !value.is_object() {
+        return Err(ErrorKind::NonMapDocument.into());
+    }
+
+    let mut props = HashMap::new();
+    flatten(String::new(), &value, &mut props)?;
+    Ok(props)
+}
+
+/// Recursively flatten a `serde_json::Value` into `props`, joining nested
+/// object keys with `.`. Arrays have no sensible scalar representation and
+/// are rejected with `UnflattenableValue` rather than silently stringified
+/// as raw JSON.
+fn flatten(prefix: String, value: &serde_json::Value, props: &mut HashMap<String, String>) -> Result<()> {
+    match *value {
+        serde_json::Value::Object(ref map) => for (key, val) in map {
+            let full_key = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            flatten(full_key, val, props)?;
+        },
+        serde_json::Value::Array(_) => {
+            return Err(ErrorKind::UnflattenableValue(prefix).into());
+        }
+        serde_json::Value::Null => {}
+        serde_json::Value::String(ref s) => {
+            props.insert(prefix, s.clone());
+        }
+        ref scalar => {
+            props.insert(prefix, scalar.to_string());
+        }
+    }
+    Ok(())
+}
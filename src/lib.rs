@@ -1,28 +1,36 @@
 //! Environment Mapping
-#![cfg_attr(feature = "cargo-clippy", allow(use_self))]
-#![feature(try_from)]
+// `error_chain!`'s expansion references a cfg that modern rustc doesn't know
+// about; this comes from the macro, not from this crate's own code.
+#![allow(unexpected_cfgs)]
 #[macro_use]
 extern crate derive_builder;
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
 extern crate getset;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
 
+mod de;
 mod error;
+mod format;
+mod source;
 
 pub use error::{Error, ErrorKind, Result};
+pub use format::Format;
+pub use source::Source;
+use serde::de::DeserializeOwned;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 use std::{env, fmt};
 
-/// Suffix for environment variables file name.
-const ENV_SUFFIX: &str = ".env";
-
 /// Environment Kinds
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Kind {
     /// `Common` or shared environment variables.
     Common,
@@ -36,12 +44,30 @@ pub enum Kind {
     Staging,
     /// `Production` specific environment variables.
     Production,
+    /// A user-defined environment kind (e.g. `qa`, `canary`, `local`) for
+    /// teams with deployment tiers beyond the named variants above.
+    Custom(String),
+}
+
+impl Kind {
+    /// The string form of this `Kind`, used to derive its property file
+    /// name (`{name}` followed by the configured `Format`'s suffix).
+    fn as_str(&self) -> &str {
+        match *self {
+            Kind::Common => "common",
+            Kind::Development => "dev",
+            Kind::Test => "test",
+            Kind::Integration => "int",
+            Kind::Staging => "stage",
+            Kind::Production => "prod",
+            Kind::Custom(ref name) => name,
+        }
+    }
 }
 
 impl fmt::Display for Kind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let kind_str: String = (*self).into();
-        write!(f, "{}", kind_str)
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -56,35 +82,27 @@ impl<'a> TryFrom<&'a str> for Kind {
             "int" => Kind::Integration,
             "stage" => Kind::Staging,
             "prod" => Kind::Production,
-            _ => return Err(ErrorKind::InvalidKind(name.to_string()).into()),
+            _ => {
+                let valid_identifier = !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                if !valid_identifier {
+                    return Err(ErrorKind::InvalidKind(name.to_string()).into());
+                }
+                Kind::Custom(name.to_string())
+            }
         };
         Ok(kind)
     }
 }
 
-impl<'a> From<Kind> for &'a str {
-    fn from(kind: Kind) -> &'a str {
-        match kind {
-            Kind::Common => "common",
-            Kind::Development => "dev",
-            Kind::Test => "test",
-            Kind::Integration => "int",
-            Kind::Staging => "stage",
-            Kind::Production => "prod",
-        }
-    }
-}
-
 impl From<Kind> for String {
     fn from(kind: Kind) -> String {
-        String::from(match kind {
-            Kind::Common => "common",
-            Kind::Development => "dev",
-            Kind::Test => "test",
-            Kind::Integration => "int",
-            Kind::Staging => "stage",
-            Kind::Production => "prod",
-        })
+        match kind {
+            Kind::Custom(name) => name,
+            named => named.as_str().to_string(),
+        }
     }
 }
 
@@ -121,6 +139,28 @@ pub struct Config {
     #[set = "pub"]
     #[builder(default = "false")]
     os: bool,
+    /// Should `${NAME}` references in property values be expanded against
+    /// the other loaded properties (and the OS environment, when `os` is
+    /// set)?
+    #[get = "pub"]
+    #[set = "pub"]
+    #[builder(default = "false")]
+    interpolate: bool,
+    /// Prefix for environment-variable overrides, e.g. `MYAPP`. When set,
+    /// a `{PREFIX}_{KEY}` process environment variable (`KEY` being the
+    /// property name uppercased with non-alphanumeric characters mapped
+    /// to `_`) overrides any value loaded from a file. Precedence is
+    /// explicit env override > kind file > common file > OS vars pulled
+    /// in via `os`.
+    #[get = "pub"]
+    #[set = "pub"]
+    #[builder(setter(into, strip_option), default = "None")]
+    env_prefix: Option<String>,
+    /// The file format property files are written in.
+    #[get = "pub"]
+    #[set = "pub"]
+    #[builder(default = "Format::Env")]
+    format: Format,
 }
 
 impl ConfigBuilder {
@@ -139,9 +179,27 @@ pub struct Environment {
     /// The key-value pairs for this environment (common + kind).
     #[get = "pub"]
     props: HashMap<String, String>,
+    /// The `Source` that provided the current value for each key.
+    origins: HashMap<String, Source>,
 }
 
-impl Environment {}
+impl Environment {
+    /// Deserialize the key/value pairs in this `Environment` into `T`,
+    /// coercing each property's string value into the type its matching
+    /// field requests (`bool`, integers, floats, `String`, and `Option<T>`
+    /// for keys that aren't present).
+    pub fn deserialize<T>(&self) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        T::deserialize(de::PropsDeserializer::new(&self.props))
+    }
+
+    /// The `Source` that provided the current value for `key`, if any.
+    pub fn origin(&self, key: &str) -> Option<&Source> {
+        self.origins.get(key)
+    }
+}
 
 /// Get the default file path.
 #[cfg(unix)]
@@ -183,57 +241,328 @@ pub fn get_config_path() -> Result<PathBuf> {
     Ok(config_path)
 }
 
-/// Read a property file into a `HashMap`.
-fn read_props_file(config: &Config, props: &mut HashMap<String, String>) -> Result<()> {
+/// Which file a `read_props_file` call is loading, for provenance tracking.
+enum FileRole {
+    /// The shared `common` property file.
+    Common,
+    /// The property file for the requested `Kind`.
+    Kind,
+}
+
+/// Read a property file into a `HashMap`, recording each key's `Source` in
+/// `origins`.
+fn read_props_file(
+    config: &Config,
+    role: FileRole,
+    props: &mut HashMap<String, String>,
+    origins: &mut HashMap<String, Source>,
+) -> Result<()> {
     let mut file_path = get_config_path()?;
     file_path.push(config.app_name());
-    let mut common_filename: String = (*config.kind()).into();
-    common_filename.push_str(ENV_SUFFIX);
+    let mut common_filename: String = config.kind().clone().into();
+    common_filename.push_str(config.format().suffix());
     file_path.push(common_filename);
-    let common_file = File::open(file_path)?;
-    let common_reader = BufReader::new(common_file);
-    for line_res in common_reader.lines() {
-        match line_res {
-            Ok(line) => {
-                if *config.comments() && line.starts_with(*config.comment_char()) {
-                    continue;
-                }
-                let mut kv = Vec::new();
-                for tok in line.split('=') {
-                    kv.push(tok);
-                }
 
-                if kv.len() != 2 {
-                    return Err(ErrorKind::InvalidProperty.into());
+    let source = match role {
+        FileRole::Common => Source::CommonFile(file_path.clone()),
+        FileRole::Kind => Source::KindFile(file_path.clone()),
+    };
+
+    match *config.format() {
+        Format::Env => {
+            let common_file = File::open(&file_path)?;
+            let common_reader = BufReader::new(common_file);
+            for line_res in common_reader.lines() {
+                let line = line_res?;
+                if let Some((key, value)) = parse_env_line(&line, config)? {
+                    origins.insert(key.clone(), source.clone());
+                    props.insert(key, value);
                 }
-                props.insert(kv[0].to_string(), kv[1].to_string());
             }
-            Err(e) => return Err(e.into()),
+        }
+        structured_format => {
+            let contents = fs::read_to_string(&file_path)?;
+            let parsed = format::parse_structured(structured_format, &contents)?;
+            for key in parsed.keys() {
+                origins.insert(key.clone(), source.clone());
+            }
+            props.extend(parsed);
         }
     }
     Ok(())
 }
 
+/// Parse a single `key=value` dotenv-style line, returning `None` for blank
+/// or whole-line comment lines. Splits only on the first `=`, allows (and
+/// strips) a leading `export ` on the key, and hands the remainder to
+/// `parse_env_value` for quote/comment handling.
+fn parse_env_line(line: &str, config: &Config) -> Result<Option<(String, String)>> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    if *config.comments() && trimmed.starts_with(*config.comment_char()) {
+        return Ok(None);
+    }
+
+    let eq_pos = match trimmed.find('=') {
+        Some(pos) => pos,
+        None => return Err(ErrorKind::InvalidProperty.into()),
+    };
+
+    let mut key = trimmed[..eq_pos].trim();
+    if key.starts_with("export ") {
+        key = key["export ".len()..].trim_start();
+    }
+
+    let value = parse_env_value(trimmed[eq_pos + 1..].trim(), config);
+
+    Ok(Some((key.to_string(), value)))
+}
+
+/// Parse the value half of a dotenv line: a double-quoted value has
+/// `\n`/`\t`/`\"` escapes interpreted, a single-quoted value is taken
+/// literally, and an unquoted value runs until an unquoted `comment_char`
+/// (when `config.comments()` is set) or end of line.
+fn parse_env_value(raw: &str, config: &Config) -> String {
+    let mut chars = raw.chars().peekable();
+    match chars.peek() {
+        Some(&'"') => {
+            chars.next();
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some('"') => value.push('"'),
+                        Some(other) => {
+                            value.push('\\');
+                            value.push(other);
+                        }
+                        None => value.push('\\'),
+                    },
+                    _ => value.push(c),
+                }
+            }
+            value
+        }
+        Some(&'\'') => {
+            chars.next();
+            let mut value = String::new();
+            for c in chars {
+                if c == '\'' {
+                    break;
+                }
+                value.push(c);
+            }
+            value
+        }
+        _ => {
+            let mut value = String::new();
+            for c in chars {
+                if *config.comments() && c == *config.comment_char() {
+                    break;
+                }
+                value.push(c);
+            }
+            value.trim_end().to_string()
+        }
+    }
+}
+
 impl TryFrom<Config> for Environment {
     type Error = Error;
 
     fn try_from(config: Config) -> Result<Environment> {
         let mut props: HashMap<String, String> = HashMap::new();
+        let mut origins: HashMap<String, Source> = HashMap::new();
         if *config.os() {
-            props.extend(env::vars());
+            for (key, value) in env::vars() {
+                origins.insert(key.clone(), Source::OsEnv);
+                props.insert(key, value);
+            }
         }
         if *config.common() {
             let common_config = ConfigBuilder::default()
                 .app_name(config.app_name().to_string())
                 .kind(Kind::Common)
+                .format(*config.format())
                 .build()?;
-            read_props_file(&common_config, &mut props)?;
+            read_props_file(&common_config, FileRole::Common, &mut props, &mut origins)?;
+        }
+        read_props_file(&config, FileRole::Kind, &mut props, &mut origins)?;
+
+        apply_env_overrides(&config, &mut props, &mut origins)?;
+
+        if *config.interpolate() {
+            interpolate_props(&mut props, &origins, *config.os())?;
         }
-        read_props_file(&config, &mut props)?;
 
         Ok(Environment {
-            current: *config.kind(),
+            current: config.kind().clone(),
             props,
+            origins,
+        })
+    }
+}
+
+/// Overwrite loaded properties with matching `{PREFIX}_{KEY}` process
+/// environment variables, when `config.env_prefix()` is set.
+///
+/// Two keys that normalize to the same `{PREFIX}_{KEY}` always reject the
+/// load with `AmbiguousEnvOverride`, even if that variable isn't currently
+/// set: a config that could silently take either property's value depending
+/// on an environment variable is a misconfiguration regardless of whether
+/// today's environment happens to expose it.
+fn apply_env_overrides(
+    config: &Config,
+    props: &mut HashMap<String, String>,
+    origins: &mut HashMap<String, Source>,
+) -> Result<()> {
+    let prefix = match config.env_prefix() {
+        Some(prefix) => prefix,
+        None => return Ok(()),
+    };
+
+    let mut by_env_name: HashMap<String, Vec<String>> = HashMap::new();
+    for key in props.keys() {
+        by_env_name
+            .entry(format!("{}_{}", prefix, normalize_env_key(key)))
+            .or_default()
+            .push(key.clone());
+    }
+
+    for (env_name, keys) in by_env_name {
+        if keys.len() > 1 {
+            return Err(ErrorKind::AmbiguousEnvOverride(env_name, keys).into());
+        }
+        if let Ok(value) = env::var(&env_name) {
+            origins.insert(keys[0].clone(), Source::EnvOverride(env_name.clone()));
+            props.insert(keys[0].clone(), value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Normalize a property key into the `KEY` half of a `{PREFIX}_{KEY}`
+/// override variable: uppercased, with non-alphanumeric characters mapped
+/// to `_`.
+fn normalize_env_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
         })
+        .collect()
+}
+
+/// Expand `${NAME}` references in every property value against the other
+/// loaded properties (and, when `use_os` is set, the OS environment).
+///
+/// Values pulled in wholesale from the OS environment (`Source::OsEnv`) are
+/// treated as opaque and are never scanned for `${NAME}` tokens: with
+/// `os: true`, `props` contains every variable in the process environment,
+/// and a literal `${...}` in one of those (a shell prompt string, say)
+/// would otherwise fail the whole load with `UndefinedReference`.
+fn interpolate_props(
+    props: &mut HashMap<String, String>,
+    origins: &HashMap<String, Source>,
+    use_os: bool,
+) -> Result<()> {
+    let keys: Vec<String> = props.keys().cloned().collect();
+    let mut resolved: HashMap<String, String> = HashMap::new();
+
+    for key in keys {
+        if resolved.contains_key(&key) {
+            continue;
+        }
+        let mut stack = Vec::new();
+        let value = resolve_value(&key, props, origins, &mut resolved, &mut stack, use_os)?;
+        resolved.insert(key, value);
     }
+
+    *props = resolved;
+    Ok(())
+}
+
+/// Resolve the `${NAME}` tokens found in the raw value for `key`, recursing
+/// into the referenced properties and memoizing the results in `resolved`.
+/// Keys currently being resolved are tracked on `stack` so that a key which
+/// transitively depends on itself is reported as a cycle rather than
+/// overflowing the stack. A key sourced from the OS environment is returned
+/// verbatim, without being scanned for `${NAME}` tokens.
+fn resolve_value(
+    key: &str,
+    raw: &HashMap<String, String>,
+    origins: &HashMap<String, Source>,
+    resolved: &mut HashMap<String, String>,
+    stack: &mut Vec<String>,
+    use_os: bool,
+) -> Result<String> {
+    if let Some(value) = resolved.get(key) {
+        return Ok(value.clone());
+    }
+
+    let raw_value = match raw.get(key) {
+        Some(v) => v.clone(),
+        None => return Err(ErrorKind::UndefinedReference(key.to_string()).into()),
+    };
+
+    if origins.get(key) == Some(&Source::OsEnv) {
+        return Ok(raw_value);
+    }
+
+    if stack.iter().any(|k| k == key) {
+        return Err(ErrorKind::InterpolationCycle(key.to_string()).into());
+    }
+    stack.push(key.to_string());
+
+    let mut result = String::new();
+    let mut chars = raw_value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        if chars.peek() == Some(&'$') {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            if lookahead.peek() == Some(&'{') {
+                // `$${NAME}` is an escaped literal `${NAME}`.
+                chars.next();
+                chars.next();
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                result.push('$');
+                result.push('{');
+                result.push_str(&name);
+                result.push('}');
+                continue;
+            }
+        }
+
+        if chars.peek() == Some(&'{') {
+            chars.next();
+            let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+            let value = if raw.contains_key(&name) {
+                resolve_value(&name, raw, origins, resolved, stack, use_os)?
+            } else if use_os {
+                env::var(&name).map_err(|_e| ErrorKind::UndefinedReference(name.clone()))?
+            } else {
+                return Err(ErrorKind::UndefinedReference(name).into());
+            };
+            result.push_str(&value);
+        } else {
+            result.push('$');
+        }
+    }
+
+    stack.pop();
+    Ok(result)
 }
@@ -0,0 +1,16 @@
+//! Provenance tracking for loaded properties.
+
+use std::path::PathBuf;
+
+/// Where a property's current value came from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// Pulled in from the OS process environment (`Config::os`).
+    OsEnv,
+    /// Loaded from the shared `common` property file.
+    CommonFile(PathBuf),
+    /// Loaded from the property file for the requested `Kind`.
+    KindFile(PathBuf),
+    /// Overwritten by a `{PREFIX}_{KEY}` environment-variable override.
+    EnvOverride(String),
+}
@@ -1,4 +1,6 @@
-#![feature(try_from)]
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate vidar;
 
 #[macro_use]
@@ -6,7 +8,33 @@ mod lifecycle;
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use vidar::{Config, ConfigBuilder, Environment, Error, ErrorKind, Kind};
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use vidar::{Config, ConfigBuilder, Environment, Error, ErrorKind, Format, Kind, Source};
+
+/// Write a single structured (non-`Env`) property file directly under the
+/// `XDG_CONFIG_HOME`/`app_name` path property files are read from, since
+/// `lifecycle::create_file` only ever writes `.env` fixtures.
+fn with_structured_fixture<F: FnOnce()>(app_name: &str, kind: Kind, suffix: &str, contents: &str, test: F) {
+    env::set_var("XDG_CONFIG_HOME", env::temp_dir());
+    env::set_var("APPDATA", env::temp_dir());
+
+    let mut dir = env::temp_dir();
+    dir.push(app_name);
+    fs::create_dir_all(&dir).expect("Unable to create fixture dir");
+
+    let mut file_name: String = kind.into();
+    file_name.push_str(suffix);
+    let mut path = dir.clone();
+    path.push(file_name);
+    let mut file = File::create(&path).expect("Unable to create fixture file");
+    file.write_all(contents.as_bytes()).expect("Unable to write fixture file");
+
+    test();
+
+    fs::remove_dir_all(&dir).expect("Unable to remove fixture dir");
+}
 
 #[test]
 fn no_file() {
@@ -25,10 +53,10 @@ fn no_file() {
 
     wrap!("no_file", Some(most), {
         match Environment::try_from(config) {
-            Ok(_) => assert!(false),
+            Ok(_) => panic!("expected a missing property file to fail to load"),
             Err(e) => match e {
-                Error(ErrorKind::Io(_), _) => assert!(true),
-                _ => assert!(false),
+                Error(ErrorKind::Io(_), _) => {}
+                _ => panic!("expected `ErrorKind::Io`, got {:?}", e),
             },
         }
     });
@@ -43,10 +71,10 @@ fn invalid_property() {
         .expect("Unable to build `Config`");
     wrap!("invalid_property", None, {
         match Environment::try_from(config) {
-            Ok(_) => assert!(false),
+            Ok(_) => panic!("expected an invalid property line to fail to load"),
             Err(e) => match e {
-                Error(ErrorKind::InvalidProperty, _) => assert!(true),
-                _ => assert!(false),
+                Error(ErrorKind::InvalidProperty, _) => {}
+                _ => panic!("expected `ErrorKind::InvalidProperty`, got {:?}", e),
             },
         }
     });
@@ -70,7 +98,7 @@ fn check_env_config(folder_name: &str, config: Config, url_value: &str) {
                 check_test_props(props);
                 assert_eq!(props.get(&"url".to_string()), Some(&url_value.to_string()));
             }
-            Err(_e) => assert!(false),
+            Err(e) => panic!("expected a valid `Environment`, got error {:?}", e),
         }
     });
 }
@@ -108,3 +136,536 @@ fn prod_config_env() {
         .expect("Unable to build Config");
     check_env_config("prod_config", config, "https://produrl.vidar.com");
 }
+
+#[test]
+fn interpolate_resolves_diamond_references() {
+    let mut most = HashMap::new();
+    most.insert(
+        Kind::Development,
+        "shared=core\nleft=${shared}-left\nright=${shared}-right\ntop=${left}|${right}",
+    );
+    let config = ConfigBuilder::default()
+        .app_name("interpolate_diamond")
+        .kind(Kind::Development)
+        .interpolate(true)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("interpolate_diamond", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        let props = env.props();
+        assert_eq!(props.get("shared"), Some(&"core".to_string()));
+        assert_eq!(props.get("left"), Some(&"core-left".to_string()));
+        assert_eq!(props.get("right"), Some(&"core-right".to_string()));
+        assert_eq!(props.get("top"), Some(&"core-left|core-right".to_string()));
+    });
+}
+
+#[test]
+fn interpolate_escapes_literal_dollar_brace() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "literal=$${HOME} stays as-is");
+    let config = ConfigBuilder::default()
+        .app_name("interpolate_escape")
+        .kind(Kind::Development)
+        .interpolate(true)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("interpolate_escape", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("literal"),
+            Some(&"${HOME} stays as-is".to_string())
+        );
+    });
+}
+
+#[test]
+fn interpolate_reports_undefined_reference() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "url=${missing}");
+    let config = ConfigBuilder::default()
+        .app_name("interpolate_undefined")
+        .kind(Kind::Development)
+        .interpolate(true)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("interpolate_undefined", Some(most), {
+        match Environment::try_from(config) {
+            Ok(_) => panic!("expected an undefined reference to fail to load"),
+            Err(e) => match e {
+                Error(ErrorKind::UndefinedReference(ref name), _) if name == "missing" => {}
+                _ => panic!("expected `ErrorKind::UndefinedReference(\"missing\")`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn interpolate_reports_cycle() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "a=${b}\nb=${a}");
+    let config = ConfigBuilder::default()
+        .app_name("interpolate_cycle")
+        .kind(Kind::Development)
+        .interpolate(true)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("interpolate_cycle", Some(most), {
+        match Environment::try_from(config) {
+            Ok(_) => panic!("expected a self-referential cycle to fail to load"),
+            Err(e) => match e {
+                Error(ErrorKind::InterpolationCycle(_), _) => {}
+                _ => panic!("expected `ErrorKind::InterpolationCycle`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn os_values_with_interpolation_syntax_are_not_scanned() {
+    env::set_var("VIDAR_TEST_OS_RAW", "${NOT_A_PROP}");
+
+    let mut most = HashMap::new();
+    most.insert(Kind::Integration, "");
+
+    let config = ConfigBuilder::default()
+        .app_name("os_interpolate")
+        .kind(Kind::Integration)
+        .os(true)
+        .interpolate(true)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("os_interpolate", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("VIDAR_TEST_OS_RAW"),
+            Some(&"${NOT_A_PROP}".to_string())
+        );
+    });
+
+    env::remove_var("VIDAR_TEST_OS_RAW");
+}
+
+#[test]
+fn env_prefix_overrides_take_precedence_over_file_values() {
+    env::set_var("VIDAR_TEST_URL", "https://overridden");
+
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "url=https://localhost");
+    let config = ConfigBuilder::default()
+        .app_name("env_override")
+        .kind(Kind::Development)
+        .env_prefix("VIDAR_TEST")
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("env_override", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("url"),
+            Some(&"https://overridden".to_string())
+        );
+    });
+
+    env::remove_var("VIDAR_TEST_URL");
+}
+
+#[test]
+fn env_prefix_reports_ambiguous_override() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "url-a=https://localhost\nurl.a=https://other");
+    let config = ConfigBuilder::default()
+        .app_name("env_ambiguous")
+        .kind(Kind::Development)
+        .env_prefix("VIDAR_TEST")
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("env_ambiguous", Some(most), {
+        match Environment::try_from(config) {
+            Ok(_) => panic!("expected colliding override keys to be rejected"),
+            Err(e) => match e {
+                Error(ErrorKind::AmbiguousEnvOverride(ref env_name, _), _)
+                    if env_name == "VIDAR_TEST_URL_A" => {}
+                _ => panic!("expected `ErrorKind::AmbiguousEnvOverride(\"VIDAR_TEST_URL_A\", _)`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn custom_kind_loads_end_to_end() {
+    let kind = Kind::try_from("qa").expect("Unable to parse custom `Kind`");
+
+    let mut most = HashMap::new();
+    most.insert(kind.clone(), "url=https://qa.vidar.com");
+    let config = ConfigBuilder::default()
+        .app_name("custom_kind")
+        .kind(kind.clone())
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("custom_kind", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(*env.current(), kind);
+        assert_eq!(
+            env.props().get("url"),
+            Some(&"https://qa.vidar.com".to_string())
+        );
+
+        let round_tripped: String = kind.clone().into();
+        assert_eq!(round_tripped, "qa");
+        assert_eq!(kind.to_string(), "qa");
+    });
+}
+
+#[test]
+fn custom_kind_rejects_invalid_identifiers() {
+    match Kind::try_from("") {
+        Ok(_) => panic!("expected an empty custom kind name to be rejected"),
+        Err(Error(ErrorKind::InvalidKind(ref name), _)) if name.is_empty() => {}
+        Err(e) => panic!("expected `ErrorKind::InvalidKind(\"\")`, got {:?}", e),
+    }
+
+    match Kind::try_from("qa env") {
+        Ok(_) => panic!("expected a custom kind name with a space to be rejected"),
+        Err(Error(ErrorKind::InvalidKind(ref name), _)) if name == "qa env" => {}
+        Err(e) => panic!("expected `ErrorKind::InvalidKind(\"qa env\")`, got {:?}", e),
+    }
+}
+
+#[test]
+fn origin_reports_kind_file_shadowing_common() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Common, "shared=common-value");
+    most.insert(Kind::Production, "shared=prod-value");
+    let config = ConfigBuilder::default()
+        .app_name("origin_shadow")
+        .common(true)
+        .kind(Kind::Production)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("origin_shadow", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(env.props().get("shared"), Some(&"prod-value".to_string()));
+        match env.origin("shared") {
+            Some(Source::KindFile(_)) => {}
+            other => panic!("expected Source::KindFile, got {:?}", other),
+        }
+    });
+}
+
+#[test]
+fn origin_reports_env_override() {
+    env::set_var("VIDAR_TEST_SHARED", "from-env");
+
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "shared=file-value");
+    let config = ConfigBuilder::default()
+        .app_name("origin_override")
+        .kind(Kind::Development)
+        .env_prefix("VIDAR_TEST")
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("origin_override", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(env.props().get("shared"), Some(&"from-env".to_string()));
+        match env.origin("shared") {
+            Some(Source::EnvOverride(ref name)) if name == "VIDAR_TEST_SHARED" => {}
+            other => panic!("expected Source::EnvOverride(\"VIDAR_TEST_SHARED\"), got {:?}", other),
+        }
+    });
+
+    env::remove_var("VIDAR_TEST_SHARED");
+}
+
+#[test]
+fn parse_env_value_handles_double_quoted_escapes() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "greeting=\"line one\\nline two\\ttabbed \\\"quoted\\\"\"");
+    let config = ConfigBuilder::default()
+        .app_name("dotenv_double_quoted")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("dotenv_double_quoted", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("greeting"),
+            Some(&"line one\nline two\ttabbed \"quoted\"".to_string())
+        );
+    });
+}
+
+#[test]
+fn parse_env_value_takes_single_quoted_values_literally() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "raw='no \\n escapes ${here}'");
+    let config = ConfigBuilder::default()
+        .app_name("dotenv_single_quoted")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("dotenv_single_quoted", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("raw"),
+            Some(&"no \\n escapes ${here}".to_string())
+        );
+    });
+}
+
+#[test]
+fn parse_env_line_strips_export_prefix() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "export url=https://localhost");
+    let config = ConfigBuilder::default()
+        .app_name("dotenv_export")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("dotenv_export", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("url"),
+            Some(&"https://localhost".to_string())
+        );
+    });
+}
+
+#[test]
+fn parse_env_value_strips_trailing_inline_comment() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "url=https://localhost # the dev server");
+    let config = ConfigBuilder::default()
+        .app_name("dotenv_inline_comment")
+        .kind(Kind::Development)
+        .comments(true)
+        .comment_char('#')
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("dotenv_inline_comment", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("url"),
+            Some(&"https://localhost".to_string())
+        );
+    });
+}
+
+#[test]
+fn parse_env_line_splits_only_on_first_equals() {
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "connection=host=localhost;port=5432");
+    let config = ConfigBuilder::default()
+        .app_name("dotenv_value_with_equals")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("dotenv_value_with_equals", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        assert_eq!(
+            env.props().get("connection"),
+            Some(&"host=localhost;port=5432".to_string())
+        );
+    });
+}
+
+#[test]
+fn format_yaml_flattens_nested_maps_to_dotted_keys() {
+    let contents = "db:\n  host: localhost\n  port: 5432\ntimeout: 30\n";
+    with_structured_fixture("format_yaml", Kind::Development, ".yaml", contents, || {
+        let config = ConfigBuilder::default()
+            .app_name("format_yaml")
+            .kind(Kind::Development)
+            .format(Format::Yaml)
+            .build()
+            .expect("Unable to build Config");
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        let props = env.props();
+        assert_eq!(props.get("db.host"), Some(&"localhost".to_string()));
+        assert_eq!(props.get("db.port"), Some(&"5432".to_string()));
+        assert_eq!(props.get("timeout"), Some(&"30".to_string()));
+    });
+}
+
+#[test]
+fn format_toml_flattens_nested_maps_to_dotted_keys() {
+    let contents = "timeout = 30\n\n[db]\nhost = \"localhost\"\nport = 5432\n";
+    with_structured_fixture("format_toml", Kind::Development, ".toml", contents, || {
+        let config = ConfigBuilder::default()
+            .app_name("format_toml")
+            .kind(Kind::Development)
+            .format(Format::Toml)
+            .build()
+            .expect("Unable to build Config");
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        let props = env.props();
+        assert_eq!(props.get("db.host"), Some(&"localhost".to_string()));
+        assert_eq!(props.get("db.port"), Some(&"5432".to_string()));
+        assert_eq!(props.get("timeout"), Some(&"30".to_string()));
+    });
+}
+
+#[test]
+fn format_json_flattens_nested_maps_to_dotted_keys() {
+    let contents = r#"{"db":{"host":"localhost","port":5432},"timeout":30}"#;
+    with_structured_fixture("format_json", Kind::Development, ".json", contents, || {
+        let config = ConfigBuilder::default()
+            .app_name("format_json")
+            .kind(Kind::Development)
+            .format(Format::Json)
+            .build()
+            .expect("Unable to build Config");
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        let props = env.props();
+        assert_eq!(props.get("db.host"), Some(&"localhost".to_string()));
+        assert_eq!(props.get("db.port"), Some(&"5432".to_string()));
+        assert_eq!(props.get("timeout"), Some(&"30".to_string()));
+    });
+}
+
+#[test]
+fn format_rejects_array_values() {
+    let contents = "items:\n  - a\n  - b\n";
+    with_structured_fixture("format_array", Kind::Development, ".yaml", contents, || {
+        let config = ConfigBuilder::default()
+            .app_name("format_array")
+            .kind(Kind::Development)
+            .format(Format::Yaml)
+            .build()
+            .expect("Unable to build Config");
+        match Environment::try_from(config) {
+            Ok(_) => panic!("expected an array value to be rejected"),
+            Err(e) => match e {
+                Error(ErrorKind::UnflattenableValue(ref key), _) if key == "items" => {}
+                _ => panic!("expected `ErrorKind::UnflattenableValue(\"items\")`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn format_rejects_non_map_document_root() {
+    let contents = "- a\n- b\n";
+    with_structured_fixture("format_non_map", Kind::Development, ".yaml", contents, || {
+        let config = ConfigBuilder::default()
+            .app_name("format_non_map")
+            .kind(Kind::Development)
+            .format(Format::Yaml)
+            .build()
+            .expect("Unable to build Config");
+        match Environment::try_from(config) {
+            Ok(_) => panic!("expected a non-map document root to be rejected"),
+            Err(e) => match e {
+                Error(ErrorKind::NonMapDocument, _) => {}
+                _ => panic!("expected `ErrorKind::NonMapDocument`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn deserialize_round_trips_mixed_scalars_and_options() {
+    #[derive(Debug, Deserialize)]
+    struct Settings {
+        name: String,
+        enabled: bool,
+        count: i32,
+        ratio: f64,
+        present: Option<String>,
+        absent: Option<String>,
+    }
+
+    let mut most = HashMap::new();
+    most.insert(
+        Kind::Development,
+        "name=widget\nenabled=true\ncount=42\nratio=3.5\npresent=yes",
+    );
+    let config = ConfigBuilder::default()
+        .app_name("deserialize_round_trip")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("deserialize_round_trip", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        let settings: Settings = env.deserialize().expect("Unable to deserialize Settings");
+        assert_eq!(settings.name, "widget");
+        assert!(settings.enabled);
+        assert_eq!(settings.count, 42);
+        assert!((settings.ratio - 3.5).abs() < f64::EPSILON);
+        assert_eq!(settings.present, Some("yes".to_string()));
+        assert_eq!(settings.absent, None);
+    });
+}
+
+#[test]
+fn deserialize_reports_missing_field() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Settings {
+        name: String,
+        missing: String,
+    }
+
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "name=widget");
+    let config = ConfigBuilder::default()
+        .app_name("deserialize_missing_field")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("deserialize_missing_field", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        match env.deserialize::<Settings>() {
+            Ok(_) => panic!("expected deserialization to fail for a missing field"),
+            Err(e) => match e {
+                Error(ErrorKind::InvalidValue(ref key, _), _) if key == "missing" => {}
+                _ => panic!("expected `ErrorKind::InvalidValue(\"missing\", _)`, got {:?}", e),
+            },
+        }
+    });
+}
+
+#[test]
+fn deserialize_reports_invalid_value() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Settings {
+        count: i32,
+    }
+
+    let mut most = HashMap::new();
+    most.insert(Kind::Development, "count=notanumber");
+    let config = ConfigBuilder::default()
+        .app_name("deserialize_invalid_value")
+        .kind(Kind::Development)
+        .build()
+        .expect("Unable to build Config");
+
+    wrap!("deserialize_invalid_value", Some(most), {
+        let env = Environment::try_from(config).expect("Unable to build Environment");
+        match env.deserialize::<Settings>() {
+            Ok(_) => panic!("expected deserialization to fail for an unparseable value"),
+            Err(e) => match e {
+                Error(ErrorKind::InvalidValue(ref key, ref expected), _)
+                    if key == "count" && expected == "i32" => {}
+                _ => panic!("expected `ErrorKind::InvalidValue(\"count\", \"i32\")`, got {:?}", e),
+            },
+        }
+    });
+}
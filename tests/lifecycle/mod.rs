@@ -5,28 +5,21 @@ use std::fs::{self, File};
 use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use vidar::Kind;
-use vidar::error::Result;
+use vidar::Result;
 
-pub const COMMON: &'static str = r"key1=val1
+pub const COMMON: &str = r"key1=val1
 key2=val2
 key3=val3";
-pub const DEV: &'static str = r"url=https://localhost";
-pub const INT: &'static str = r"";
-pub const TEST: &'static str = r"# This is a comment
+pub const DEV: &str = r"url=https://localhost";
+pub const INT: &str = r"";
+pub const TEST: &str = r"# This is a comment
 url=https://testurl.vidar.com";
-pub const STAGE: &'static str = r"this is a bad property";
-pub const PROD: &'static str = r"url=https://produrl.vidar.com
+pub const STAGE: &str = r"this is a bad property";
+pub const PROD: &str = r"url=https://produrl.vidar.com
 creds=secret";
 
 fn create_file(kind: Kind, contents: &str, path: &mut PathBuf) -> Result<()> {
-    let file_name = match kind {
-        Kind::Common => "common.env",
-        Kind::Development => "dev.env",
-        Kind::Integration => "int.env",
-        Kind::Test => "test.env",
-        Kind::Staging => "stage.env",
-        Kind::Production => "prod.env",
-    };
+    let file_name = format!("{}.env", kind);
 
     path.push(file_name);
     let common = File::create(&path)?;
@@ -38,6 +31,12 @@ fn create_file(kind: Kind, contents: &str, path: &mut PathBuf) -> Result<()> {
 }
 
 pub fn setup(subfolder: &str, content_map: Option<HashMap<Kind, &str>>) -> Result<()> {
+    // Property files are read from `{XDG_CONFIG_HOME}/{app_name}` (or
+    // `{APPDATA}/{app_name}` on Windows), so point it at `temp_dir()` and
+    // rely on every test's `app_name` matching its `subfolder`.
+    env::set_var("XDG_CONFIG_HOME", env::temp_dir());
+    env::set_var("APPDATA", env::temp_dir());
+
     let mut path = env::temp_dir();
     path.push(subfolder);
     fs::create_dir_all(&path)?;